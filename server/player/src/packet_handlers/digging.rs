@@ -5,15 +5,17 @@
 //! swapping items out to the offhand, and dropping items.
 
 use crate::{ItemTimedUse, IteratorExt};
-use entity::InventoryExt;
 use feather_core::blocks::BlockId;
 use feather_core::inventory::{slot, Area, Inventory, Slot, SlotIndex};
-use feather_core::items::{Item, ItemStack};
-use feather_core::network::packets::{PlayerDigging, PlayerDiggingStatus};
+use feather_core::items::{Enchantment, Item, ItemStack};
+use feather_core::Gamemode;
+use feather_core::network::packets::{
+    AcknowledgePlayerDigging, BlockChange, PlayerDigging, PlayerDiggingStatus,
+};
 use feather_core::util::{BlockPosition, Position};
 use feather_server_types::{
     BlockUpdateCause, CanBreak, CanInstaBreak, EntitySpawnEvent, Game, HeldItem,
-    InventoryUpdateEvent, ItemDropEvent, PacketBuffers, Velocity, PLAYER_EYE_HEIGHT, TPS,
+    InventoryUpdateEvent, ItemDropEvent, Network, PacketBuffers, Velocity, PLAYER_EYE_HEIGHT, TPS,
 };
 use feather_server_util::{charge_from_ticks_held, compute_projectile_velocity};
 use fecs::{Entity, IntoQuery, Read, World, Write};
@@ -67,13 +69,61 @@ fn handle_digging(game: &mut Game, world: &mut World, player: Entity, packet: Pl
         return;
     }
 
-    match packet.status {
+    let pos = packet.location;
+    let status = packet.status;
+
+    let successful = match status {
         PlayerDiggingStatus::StartedDigging => handle_started_digging(game, world, player, packet),
         PlayerDiggingStatus::CancelledDigging => handle_cancelled_digging(game, world, player),
         PlayerDiggingStatus::FinishedDigging => {
             handle_finished_digging(game, world, player, packet)
         }
         _ => unreachable!(),
+    };
+
+    // Confirm or veto the digging state transition to the client. On rejection
+    // the real block state is re-sent so the client snaps back instead of
+    // showing a ghost hole until the next block update.
+    acknowledge_digging(game, world, player, pos, status, successful);
+}
+
+/// Sends an acknowledgement for a digging state transition (Start/Abort/Stop)
+/// at `pos`. When the action was not `successful`, the real block state is
+/// re-sent so the client reverts its predicted change.
+///
+// NOTE: this relies on a clientbound `AcknowledgePlayerDigging` packet
+// (`location`, `block`, `status`, `successful`) being added to
+// `feather_core::network::packets` and registered in the play-state encoder
+// alongside the existing `BlockChange`.
+fn acknowledge_digging(
+    game: &Game,
+    world: &World,
+    player: Entity,
+    pos: BlockPosition,
+    status: PlayerDiggingStatus,
+    successful: bool,
+) {
+    // Processing the action may have disconnected the player (e.g. digging in
+    // an unloaded chunk). Don't send to a torn-down entity.
+    if !world.has::<Network>(player) {
+        return;
+    }
+
+    let block = game.block_at(pos).unwrap_or_default();
+
+    let network = world.get::<Network>(player);
+    network.send(AcknowledgePlayerDigging {
+        location: pos,
+        block: block.vanilla_id() as i32,
+        status,
+        successful,
+    });
+
+    if !successful {
+        network.send(BlockChange {
+            location: pos,
+            block: block.vanilla_id() as i32,
+        });
     }
 }
 
@@ -97,12 +147,14 @@ pub struct FinishDiggingEvent {
     pub digging: Digging,
 }
 
+/// Handles a `StartedDigging` transition, returning whether the action was
+/// accepted (so it can be acknowledged to the client).
 fn handle_started_digging(
     game: &mut Game,
     world: &mut World,
     player: Entity,
     packet: PlayerDigging,
-) {
+) -> bool {
     // Delete old `Digging`, if it exists
     let _ = world.remove::<Digging>(player);
 
@@ -113,9 +165,9 @@ fn handle_started_digging(
         .distance_squared_to(*world.get::<Position>(player))
         > MAX_DIG_RADIUS_SQUARED
     {
-        // Ignore the packet.
+        // Reject the packet.
         log::trace!("player {:?} tried to dig too far", player);
-        return;
+        return false;
     }
 
     // If the player can insta-break, or the block has hardness 0, then they can already break the block.
@@ -127,7 +179,7 @@ fn handle_started_digging(
             .hardness()
             < 0.01
     {
-        dig(game, world, player, packet.location);
+        dig(game, world, player, packet.location)
     } else {
         // Insert new `Digging`.
         let block = game.block_at(packet.location).unwrap_or_default();
@@ -144,6 +196,8 @@ fn handle_started_digging(
             )
             .unwrap();
         game.handle(world, StartDiggingEvent { player });
+
+        true
     }
 }
 
@@ -197,21 +251,26 @@ pub fn advance_dig_progress(game: &mut Game, world: &mut World) {
     );
 }
 
-fn handle_cancelled_digging(game: &mut Game, world: &mut World, player: Entity) {
+/// Handles a `CancelledDigging` transition. Aborting a dig always succeeds.
+fn handle_cancelled_digging(game: &mut Game, world: &mut World, player: Entity) -> bool {
     let digging = world.try_get::<Digging>(player).map(|d| *d);
     let _ = world.remove::<Digging>(player);
 
     if let Some(digging) = digging {
         game.handle(world, FinishDiggingEvent { player, digging });
     }
+
+    true
 }
 
+/// Handles a `FinishedDigging` transition, returning whether the block was
+/// actually broken (so it can be acknowledged to the client).
 fn handle_finished_digging(
     game: &mut Game,
     world: &mut World,
     player: Entity,
     packet: PlayerDigging,
-) {
+) -> bool {
     let digging = match world.try_get::<Digging>(player) {
         Some(digging) => *digging,
         None => {
@@ -226,7 +285,7 @@ fn handle_finished_digging(
                 // Player can't insta-break and has
                 // not sent StartedDigging.
                 // They cannot finish.
-                return;
+                return false;
             }
         }
     };
@@ -234,17 +293,39 @@ fn handle_finished_digging(
     let _ = world.remove::<Digging>(player);
 
     if digging.pos != packet.location {
-        return;
+        return false;
     }
 
-    // Attempt to break the block
-    dig(game, world, player, digging.pos);
+    // Attempt to break the block. If it failed (e.g. unloaded chunk), the
+    // player has been disconnected; don't report success. `acknowledge_digging`
+    // skips the ack once the player's `Network` is gone.
+    if !dig(game, world, player, digging.pos) {
+        return false;
+    }
 
     // Finished
     game.handle(world, FinishDiggingEvent { player, digging });
+
+    true
 }
 
-fn dig(game: &mut Game, world: &mut World, player: Entity, pos: BlockPosition) {
+/// Event triggered when an item's durability is exhausted and the stack is
+/// removed from the player's inventory, so that break sounds/animations can
+/// hook in.
+#[derive(Copy, Clone, Debug)]
+pub struct ItemBreakEvent {
+    /// The player whose item broke.
+    pub player: Entity,
+    /// The kind of item which broke.
+    pub item: Item,
+}
+
+/// Breaks the block at `pos`, returning `false` if the player was disconnected
+/// (e.g. the chunk was unloaded) and thus must not be messaged afterwards.
+fn dig(game: &mut Game, world: &mut World, player: Entity, pos: BlockPosition) -> bool {
+    // Read the hardness before the block is replaced with air.
+    let hardness = game.block_at(pos).unwrap_or_default().kind().hardness();
+
     if !game.set_block_at(world, pos, BlockId::air(), BlockUpdateCause::Entity(player)) {
         game.disconnect(
             player,
@@ -254,6 +335,86 @@ fn dig(game: &mut Game, world: &mut World, player: Entity, pos: BlockPosition) {
                 pos
             ),
         );
+        return false;
+    }
+
+    // Charge one point of wear to the tool used to break the block. Instant
+    // breaks (0-hardness blocks such as flowers or tall grass) cost no
+    // durability, matching the insta-break branch in `handle_started_digging`.
+    // Blocks are always dug with the main hand.
+    if hardness >= 0.01 {
+        let held_item = world.get::<HeldItem>(player).0;
+        damage_held_item(game, world, player, slot(Area::Hotbar, held_item));
+    }
+
+    true
+}
+
+/// Returns the maximum durability of an item, or `None` if the item does not
+/// wear out (blocks, food, and other non-tools). Tools and weapons derive
+/// their durability from their [`ToolMaterial`]; the bow has a fixed value.
+///
+// NOTE: the durability subsystem relies on two companion additions in
+// `feather_core::items`: a `damage: u32` field on `ItemStack` (defaulting to 0
+// in `ItemStack::new`) and a `ToolMaterial::durability()` accessor alongside
+// the existing `dig_multiplier()`.
+fn max_durability(item: Item) -> Option<u32> {
+    if item == Item::Bow {
+        return Some(384);
+    }
+
+    item.tool_material().map(|mat| mat.durability())
+}
+
+/// Applies one point of durability damage to the item in `item_slot` (the slot
+/// actually used for the action, which may be the off-hand). Unbreakable items
+/// (those without a max durability, e.g. non-tools) are skipped. When
+/// durability is exhausted the stack is removed, an [`InventoryUpdateEvent`] is
+/// emitted and an [`ItemBreakEvent`] is fired.
+fn damage_held_item(game: &mut Game, world: &mut World, player: Entity, item_slot: SlotIndex) {
+    let inventory = world.get::<Inventory>(player);
+
+    let stack = match inventory.item_at(item_slot.area, item_slot.slot).unwrap() {
+        Some(stack) => stack,
+        None => return,
+    };
+
+    let max_durability = match max_durability(stack.ty) {
+        Some(max) => max,
+        // Item does not wear out.
+        None => return,
+    };
+
+    let broke = stack.damage + 1 >= max_durability;
+    if broke {
+        inventory
+            .remove_item_at(item_slot.area, item_slot.slot)
+            .unwrap();
+    } else {
+        let mut worn = stack;
+        worn.damage += 1;
+        inventory
+            .set_item_at(item_slot.area, item_slot.slot, worn)
+            .unwrap();
+    }
+    drop(inventory);
+
+    game.handle(
+        world,
+        InventoryUpdateEvent {
+            slots: smallvec![item_slot],
+            player,
+        },
+    );
+
+    if broke {
+        game.handle(
+            world,
+            ItemBreakEvent {
+                player,
+                item: stack.ty,
+            },
+        );
     }
 }
 
@@ -328,65 +489,271 @@ fn handle_drop_item_stack(
     }
 }
 
+/// Event emitted when a player finishes eating or drinking a consumable,
+/// instructing the hunger system to restore food and saturation.
+#[derive(Copy, Clone, Debug)]
+pub struct FoodUpdateEvent {
+    pub player: Entity,
+    /// Hunger points to restore.
+    pub food: i32,
+    /// Saturation to restore.
+    pub saturation: f32,
+}
+
+/// A player's hunger state.
+#[derive(Copy, Clone, Debug)]
+pub struct Hunger {
+    /// Food level, in the range `0..=20`.
+    pub food: i32,
+    /// Saturation, which can never exceed the current food level.
+    pub saturation: f32,
+}
+
+impl Hunger {
+    /// Restores `food` and `saturation`, clamping to the vanilla limits (food
+    /// is capped at 20, saturation at the resulting food level).
+    fn restore(&mut self, food: i32, saturation: f32) {
+        self.food = (self.food + food).min(20);
+        self.saturation = (self.saturation + saturation).min(self.food as f32);
+    }
+}
+
+/// Applies the food and saturation from a [`FoodUpdateEvent`] to the player's
+/// [`Hunger`]. Without this handler, eating would decrement the stack but
+/// restore nothing.
+#[fecs::event_handler]
+pub fn on_food_update(event: &FoodUpdateEvent, world: &mut World) {
+    if let Some(mut hunger) = world.try_get_mut::<Hunger>(event.player) {
+        hunger.restore(event.food, event.saturation);
+    }
+}
+
+/// Static properties of a consumable item (food or potion).
+#[derive(Copy, Clone, Debug)]
+struct FoodProperties {
+    /// Hunger points restored on consumption.
+    hunger: i32,
+    /// Saturation restored on consumption.
+    saturation: f32,
+    /// Number of ticks the item must be held before the action completes.
+    /// Most foods take 32 ticks (1.6 seconds).
+    use_duration: u64,
+    /// Item left in the slot once the stack is fully consumed, for consumables
+    /// with a container (e.g. mushroom stew leaves a bowl, a potion leaves a
+    /// glass bottle).
+    container: Option<Item>,
+}
+
+/// Returns the consumption properties for an item, or `None` if the item
+/// cannot be eaten or drunk.
+fn food_properties(item: Item) -> Option<FoodProperties> {
+    // Hunger/saturation values from https://minecraft.gamepedia.com/Hunger.
+    let (hunger, saturation, container) = match item {
+        Item::Apple => (4, 2.4, None),
+        Item::Bread => (5, 6.0, None),
+        Item::BakedPotato => (5, 6.0, None),
+        Item::Carrot => (3, 3.6, None),
+        Item::CookedBeef => (8, 12.8, None),
+        Item::CookedChicken => (6, 7.2, None),
+        Item::CookedPorkchop => (8, 12.8, None),
+        Item::CookedMutton => (6, 9.6, None),
+        Item::CookedCod => (5, 6.0, None),
+        Item::CookedSalmon => (6, 9.6, None),
+        Item::GoldenApple => (4, 9.6, None),
+        Item::MushroomStew => (6, 7.2, Some(Item::Bowl)),
+        Item::RabbitStew => (10, 12.0, Some(Item::Bowl)),
+        Item::BeetrootSoup => (6, 7.2, Some(Item::Bowl)),
+        Item::MilkBucket => (0, 0.0, Some(Item::Bucket)),
+        Item::Potion => (0, 0.0, Some(Item::GlassBottle)),
+        _ => return None,
+    };
+
+    Some(FoodProperties {
+        hunger,
+        saturation,
+        use_duration: 32,
+        container,
+    })
+}
+
 /// Handles food consumption and shooting arrows.
 fn handle_consume_item(game: &mut Game, world: &mut World, player: Entity, packet: PlayerDigging) {
     assert_eq!(packet.status, PlayerDiggingStatus::ConsumeItem);
 
-    // TODO: Fallback to off-hand if main-hand is not a consumable
+    let held_item = world.get::<HeldItem>(player).0;
     let inventory = world.get::<Inventory>(player);
-    let used_item = inventory.item_in_main_hand(player, world);
+    let used_item = wielded_item_or_hand(&inventory, held_item);
+    drop(inventory);
 
-    if let Some(item) = used_item {
+    if let Some((item_slot, item)) = used_item {
         if item.ty == Item::Bow {
-            drop(inventory);
-            handle_shoot_bow(game, world, player);
+            handle_shoot_bow(game, world, player, item_slot, item);
+        } else if let Some(props) = food_properties(item.ty) {
+            handle_eat_food(game, world, player, item_slot, props);
         }
-        // TODO: Food, potions
     }
 }
 
-fn handle_shoot_bow(game: &mut Game, world: &mut World, player: Entity) {
+/// Resolves the item the player is currently using: the main-hand item if one
+/// is held, otherwise the off-hand item. Returns the slot alongside the stack
+/// so callers can write a consumed stack back to the correct hand.
+fn wielded_item_or_hand(inventory: &Inventory, held_item: usize) -> Option<(SlotIndex, ItemStack)> {
+    if let Some(main_hand) = inventory.item_at(Area::Hotbar, held_item).unwrap() {
+        return Some((slot(Area::Hotbar, held_item), main_hand));
+    }
+
+    if let Some(off_hand) = inventory.item_at(Area::Offhand, 0).unwrap() {
+        return Some((slot(Area::Offhand, 0), off_hand));
+    }
+
+    None
+}
+
+/// Completes a food or potion consumption started via `ItemTimedUse`.
+///
+/// Modeled on [`handle_shoot_bow`]: the `ItemTimedUse` component records the
+/// tick the player began using the item, and the action only completes once
+/// the item has been held for its full use duration.
+fn handle_eat_food(
+    game: &mut Game,
+    world: &mut World,
+    player: Entity,
+    item_slot: SlotIndex,
+    props: FoodProperties,
+) {
+    let timed_use = match world.try_get::<ItemTimedUse>(player) {
+        Some(timed_use) => timed_use,
+        // Spam clicking can lead to this system running before the UseItem
+        // system adds the component. In that case just return.
+        None => return,
+    };
+
+    let time_held = game.tick_count - timed_use.tick_start;
+    drop(timed_use);
+
+    // The player hasn't held the item long enough to finish eating.
+    if time_held < props.use_duration {
+        return;
+    }
+
+    // Apply the food/health change *before* mutating the inventory: a health
+    // change could kill the player and trigger inventory clearing, which would
+    // otherwise corrupt the slot write performed below.
+    game.handle(
+        world,
+        FoodUpdateEvent {
+            player,
+            food: props.hunger,
+            saturation: props.saturation,
+        },
+    );
+
+    // Reduce the stack, leaving the container item (e.g. a bowl or glass
+    // bottle) behind when the last item is consumed.
     let inventory = world.get::<Inventory>(player);
-    let arrow_to_consume: Option<(SlotIndex, ItemStack)> = find_arrow(&inventory);
-    // Unnecessary until more gamemodes are supported
-    /*
-    if player.gamemode == Gamemode::Survival || player.gamemode == Gamemode::Adventure {
-        // If no arrow was found, don't shoot
-        let arrow_to_consume = arrow_to_consume.clone();
-        if arrow_to_consume.is_none() {
-            debug!("Tried to shoot bow with no arrows.");
-            return;
+    if let Some(stack) = inventory.item_at(item_slot.area, item_slot.slot).unwrap() {
+        if stack.amount <= 1 {
+            match props.container {
+                Some(container) => inventory
+                    .set_item_at(item_slot.area, item_slot.slot, ItemStack::new(container, 1))
+                    .unwrap(),
+                None => inventory
+                    .remove_item_at(item_slot.area, item_slot.slot)
+                    .unwrap(),
+            }
+        } else {
+            inventory
+                .set_item_at(
+                    item_slot.area,
+                    item_slot.slot,
+                    ItemStack::new(stack.ty, stack.amount - 1),
+                )
+                .unwrap();
         }
+    }
+    drop(inventory);
 
-        // Consume arrow
-        let (arrow_slot, arrow_stack) = arrow_to_consume.unwrap();
-        let mut arrow_stack: ItemStack = arrow_stack;
-        arrow_stack.amount -= 1;
+    game.handle(
+        world,
+        InventoryUpdateEvent {
+            slots: smallvec![item_slot],
+            player,
+        },
+    );
 
-        inventory.set_item_at(arrow_slot, arrow_stack);
-        inventory_updates.single_write(InventoryUpdateEvent {
-            slots: smallvec![arrow_slot],
-            player: entity,
-        });
+    // Reuse the `ItemTimedUse` removal pattern from `handle_shoot_bow`.
+    world.remove::<ItemTimedUse>(player).unwrap();
+}
+
+fn handle_shoot_bow(
+    game: &mut Game,
+    world: &mut World,
+    player: Entity,
+    bow_slot: SlotIndex,
+    bow: ItemStack,
+) {
+    let gamemode = *world.get::<Gamemode>(player);
+
+    let inventory = world.get::<Inventory>(player);
+    let arrow_to_consume: Option<(SlotIndex, ItemStack)> = find_arrow(&inventory);
+
+    // Verify the shot will actually fire *before* mutating the inventory, so a
+    // spam-clicked bow doesn't lose an arrow without spawning one. Spam
+    // clicking can lead to this system running before the UseItem system adds
+    // the component; in that case just return (mirroring `handle_eat_food`).
+    if !world.has::<ItemTimedUse>(player) {
+        return;
     }
-    */
 
-    drop(inventory); // Inventory no longer used.
+    // In Survival and Adventure the bow consumes an arrow and refuses to fire
+    // when the player has none. Creative players fire freely.
+    if let Gamemode::Survival | Gamemode::Adventure = gamemode {
+        let (arrow_slot, mut arrow_stack) = match arrow_to_consume.clone() {
+            Some(arrow) => arrow,
+            None => {
+                // If no arrow was found, don't shoot.
+                log::debug!("Tried to shoot bow with no arrows.");
+                return;
+            }
+        };
+
+        // Infinity bows don't consume plain arrows (but still consume tipped
+        // and spectral arrows).
+        let infinite = has_infinity(&bow) && arrow_stack.ty == Item::Arrow;
+        if !infinite {
+            arrow_stack.amount -= 1;
+            if arrow_stack.amount == 0 {
+                inventory
+                    .remove_item_at(arrow_slot.area, arrow_slot.slot)
+                    .unwrap();
+            } else {
+                inventory
+                    .set_item_at(arrow_slot.area, arrow_slot.slot, arrow_stack)
+                    .unwrap();
+            }
+
+            drop(inventory);
+            game.handle(
+                world,
+                InventoryUpdateEvent {
+                    slots: smallvec![arrow_slot],
+                    player,
+                },
+            );
+        } else {
+            drop(inventory); // Inventory no longer used.
+        }
+    } else {
+        drop(inventory); // Inventory no longer used.
+    }
 
     let _arrow_type: Item = match arrow_to_consume {
         None => Item::Arrow, // Default to generic arrow in creative mode with none in inventory
         Some((_, arrow_stack)) => arrow_stack.ty,
     };
 
-    let timed_use = world.try_get::<ItemTimedUse>(player);
-
-    // Spam clicking can lead to a scenario where this system is called before the UseItem system adds the component
-    // In that case just return.
-    if timed_use.is_none() {
-        return;
-    }
-
-    let timed_use = timed_use.unwrap();
+    // Presence was verified above before any arrow was consumed.
+    let timed_use = world.get::<ItemTimedUse>(player);
 
     let mut time_held = game.tick_count - timed_use.tick_start;
 
@@ -424,12 +791,15 @@ fn handle_shoot_bow(game: &mut Game, world: &mut World, player: Entity) {
         .build()
         .spawn_in(world);
     game.handle(world, EntitySpawnEvent { entity });
+
+    // Charge one point of wear to the bow in whichever hand fired it.
+    damage_held_item(game, world, player, bow_slot);
 }
 
 fn find_arrow(inventory: &Inventory) -> Option<(SlotIndex, ItemStack)> {
     // Order of priority is: off-hand, hotbar (0 to 8), rest of inventory
 
-    if let Some(offhand) = inventory.item_at(Area::Hotbar, 0).unwrap() {
+    if let Some(offhand) = inventory.item_at(Area::Offhand, 0).unwrap() {
         if is_arrow_item(offhand.ty) {
             return Some((slot(Area::Offhand, 0), offhand));
         }
@@ -459,3 +829,54 @@ fn is_arrow_item(item: Item) -> bool {
         _ => false,
     }
 }
+
+/// Returns whether a bow carries the Infinity enchantment.
+///
+// NOTE: Infinity detection relies on an enchantment data model in
+// `feather_core::items`: an `Enchantment` enum (with an `Infinity` variant) and
+// an `enchantments` list on `ItemStack` whose entries expose a `ty` field.
+fn has_infinity(bow: &ItemStack) -> bool {
+    bow.enchantments
+        .iter()
+        .any(|enchantment| enchantment.ty == Enchantment::Infinity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eating_restores_food_and_saturation() {
+        let mut hunger = Hunger {
+            food: 10,
+            saturation: 2.0,
+        };
+        let props = food_properties(Item::Bread).unwrap();
+
+        hunger.restore(props.hunger, props.saturation);
+
+        assert_eq!(hunger.food, 15);
+        // Saturation restored in full: 8.0 stays below the new food level of 15.
+        assert_eq!(hunger.saturation, 8.0);
+    }
+
+    #[test]
+    fn food_level_is_capped_at_twenty() {
+        let mut hunger = Hunger {
+            food: 19,
+            saturation: 0.0,
+        };
+
+        hunger.restore(8, 12.0);
+
+        assert_eq!(hunger.food, 20);
+        assert_eq!(hunger.saturation, 12.0);
+    }
+
+    #[test]
+    fn stew_leaves_behind_a_bowl() {
+        assert_eq!(food_properties(Item::MushroomStew).unwrap().container, Some(Item::Bowl));
+        assert_eq!(food_properties(Item::Bread).unwrap().container, None);
+        assert!(food_properties(Item::Stone).is_none());
+    }
+}